@@ -0,0 +1,124 @@
+//! Pluggable headless rendering backends.
+//!
+//! `Gpu` no longer hard-codes glium/glutin: it is generic over a [`Backend`]
+//! implementation that supplies whatever GPU objects it needs (textures,
+//! programs, vertex/index buffers) behind a small set of associated types.
+//! `opengl` provides the original glium-based implementation; `wgpu` is a
+//! parallel implementation on top of wgpu, selected by the `wgpu` cargo
+//! feature. Exactly one of `opengl`/`wgpu` must be enabled; `opengl` is the
+//! default so existing consumers keep building unchanged.
+
+use crate::gpu::GpuVertex;
+use crate::Result;
+
+#[cfg(feature = "opengl")]
+pub mod opengl;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+
+#[cfg(feature = "opengl")]
+pub use self::opengl::OpenglBackend as DefaultBackend;
+#[cfg(all(feature = "wgpu", not(feature = "opengl")))]
+pub use self::wgpu::WgpuBackend as DefaultBackend;
+
+/// A uniform value in a form no single backend owns. Backends translate this
+/// into whatever their shader binding API expects (glium's `UniformValue`,
+/// a wgpu uniform buffer write, etc).
+#[derive(Debug, Clone, Copy)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec4([f32; 4]),
+    Mat3([[f32; 3]; 3]),
+}
+
+/// Portable shader source. `opengl` feeds `glsl` to glium directly; `wgpu`
+/// translates it to WGSL (via naga) at compile time.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderSource {
+    pub vertex_glsl: &'static str,
+    pub fragment_glsl: &'static str,
+}
+
+/// Stencil configuration for one draw call, used to implement clip masks:
+/// a clip path is first rendered with `WriteMask` (color writes disabled),
+/// then the clipped element's content is rendered with `TestMask` against
+/// the same reference value. `WriteMask` replaces the stencil value with
+/// `reference` wherever the clip path covers, whatever was there before, and
+/// `TestMask` passes only where the stencil equals `reference`. There is one
+/// stencil plane per texture and `Gpu` uses a single reference, so clips do
+/// not nest.
+#[derive(Debug, Clone, Copy)]
+pub enum StencilOp {
+    WriteMask { reference: u8 },
+    TestMask { reference: u8 },
+}
+
+/// The GPU operations `Gpu` actually needs, factored out so a headless
+/// composition can be rendered on any device backend can reach.
+pub trait Backend: Sized {
+    /// A compiled, linked shader program.
+    type Program;
+    /// An offscreen render target elements are composited into.
+    type Texture;
+    /// The uploaded vertex/index pair for one draw call.
+    type Buffers;
+    /// A raw RGBA8 image read back from a `Texture`.
+    type RamImage;
+
+    fn new() -> Result<Self>;
+
+    fn compile_shader(&self, source: ShaderSource) -> Result<Self::Program>;
+
+    /// Builds an offscreen target with a stencil plane attached, so clip
+    /// masks can be written and tested against it without a separate
+    /// texture.
+    fn build_texture(&self, width: u32, height: u32) -> Result<Self::Texture>;
+
+    fn build_buffers(&self, vertices: &[GpuVertex], indices: &[u32]) -> Result<Self::Buffers>;
+
+    /// Draw `buffers` into `texture` using `program`, blending over whatever
+    /// is already there. `stencil` is `None` for ordinary unclipped content;
+    /// see [`StencilOp`] for the clip-mask write/test passes. `pattern` binds
+    /// a `Paint::Pattern`'s texture as `patternTex`; it is a separate
+    /// parameter rather than a `UniformValue` because a texture handle isn't
+    /// a value a backend-agnostic uniform can carry (same reasoning as
+    /// `draw_fullscreen`'s `source`).
+    fn draw(
+        &self,
+        texture: &Self::Texture,
+        buffers: &Self::Buffers,
+        program: &Self::Program,
+        uniforms: &[(String, UniformValue)],
+        stencil: Option<StencilOp>,
+        pattern: Option<&Self::SampledTexture>,
+    ) -> Result<()>;
+
+    /// Resets `texture`'s stencil plane to 0, e.g. after a clipped element
+    /// has been drawn so the next element isn't masked by a stale clip.
+    fn clear_stencil(&self, texture: &Self::Texture) -> Result<()>;
+
+    fn read_to_ram(&self, texture: &Self::Texture) -> Result<Self::RamImage>;
+
+    fn texture_size(&self, texture: &Self::Texture) -> (u32, u32);
+
+    /// A `Texture` resolved into a form that can be bound as a shader
+    /// sampler, for feeding one pass's output into the next as a
+    /// full-frame effect's input.
+    type SampledTexture;
+
+    fn resolve_to_sampleable(&self, texture: &Self::Texture) -> Result<Self::SampledTexture>;
+
+    /// Draw `buffers` (expected to be a fullscreen quad) into `target`,
+    /// sampling `source` as `tex` in the fragment shader. Used by the
+    /// post-processing effect pipeline; unlike [`Backend::draw`] this
+    /// always overwrites `target` rather than blending.
+    fn draw_fullscreen(
+        &self,
+        target: &Self::Texture,
+        program: &Self::Program,
+        source: &Self::SampledTexture,
+        buffers: &Self::Buffers,
+        uniforms: &[(String, UniformValue)],
+    ) -> Result<()>;
+}
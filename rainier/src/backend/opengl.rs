@@ -0,0 +1,322 @@
+//! The original glium/glutin headless OpenGL backend.
+
+use super::{Backend, ShaderSource, StencilOp, UniformValue as BackendUniformValue};
+use crate::gpu::GpuVertex;
+use crate::Result;
+use glium::{
+    backend::glutin::headless::Headless,
+    draw_parameters::{Stencil, StencilOperation, StencilTest},
+    framebuffer::{DepthStencilRenderBuffer, SimpleFrameBuffer},
+    index::PrimitiveType,
+    texture::{
+        texture2d::Texture2d,
+        texture2d_multisample::Texture2dMultisample,
+        DepthStencilFormat,
+        MipmapsOption,
+        RawImage2d,
+        UncompressedFloatFormat,
+    },
+    uniforms::{MagnifySamplerFilter, UniformValue, Uniforms},
+    Blend,
+    BlendingFunction,
+    DrawParameters,
+    IndexBuffer,
+    LinearBlendingFactor,
+    Program,
+    Surface,
+    VertexBuffer,
+};
+use glutin::dpi::PhysicalSize;
+use std::rc::Rc;
+
+pub struct OpenglBuffers {
+    vertices: VertexBuffer<GpuVertex>,
+    indices: IndexBuffer<u32>,
+}
+
+/// An offscreen render target plus the stencil plane clip masks are written
+/// into and tested against.
+pub struct OpenglTexture {
+    color: Texture2dMultisample,
+    stencil: DepthStencilRenderBuffer,
+}
+
+fn to_glium_uniform(value: BackendUniformValue) -> UniformValue<'static> {
+    match value {
+        BackendUniformValue::Float(v) => UniformValue::Float(v),
+        BackendUniformValue::Vec2(v) => UniformValue::Vec2(v),
+        BackendUniformValue::Vec4(v) => UniformValue::Vec4(v),
+        BackendUniformValue::Mat3(v) => UniformValue::Mat3(v),
+    }
+}
+
+/// A `tex` sampler alongside the named uniforms, for the full-frame effect
+/// pass.
+struct FullscreenUniforms<'a> {
+    named: &'a [(String, BackendUniformValue)],
+    source: &'a Texture2d,
+}
+
+impl<'a> Uniforms for FullscreenUniforms<'a> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut f: F) {
+        for (name, value) in self.named {
+            f(name.as_str(), to_glium_uniform(*value));
+        }
+        f(
+            "tex",
+            UniformValue::Texture2d(
+                self.source,
+                Some(glium::uniforms::SamplerBehavior {
+                    magnify_filter: MagnifySamplerFilter::Linear,
+                    ..Default::default()
+                }),
+            ),
+        );
+    }
+}
+
+/// The named uniforms plus the `patternTex` sampler `default.frag` always
+/// declares. For elements that aren't `Paint::Pattern` filled, `draw` sets
+/// `pattern` to `dummy_texture` so the compiled
+/// program (which references `patternTex` regardless of which paint branch
+/// actually runs) always has a value to sample.
+struct ContentUniforms<'a> {
+    named: &'a [(String, BackendUniformValue)],
+    pattern: &'a Texture2d,
+}
+
+impl<'a> Uniforms for ContentUniforms<'a> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut f: F) {
+        for (name, value) in self.named {
+            f(name.as_str(), to_glium_uniform(*value));
+        }
+        f(
+            "patternTex",
+            UniformValue::Texture2d(
+                self.pattern,
+                Some(glium::uniforms::SamplerBehavior {
+                    magnify_filter: MagnifySamplerFilter::Linear,
+                    ..Default::default()
+                }),
+            ),
+        );
+    }
+}
+
+pub struct OpenglBackend {
+    ctx: Rc<Headless>,
+    /// Bound as `patternTex` for draws whose `Paint` isn't a `Pattern`, so
+    /// `default.frag`'s always-declared `patternTex` uniform never goes
+    /// unset.
+    dummy_texture: Texture2d,
+}
+
+impl Backend for OpenglBackend {
+    type Program = Program;
+    type Texture = OpenglTexture;
+    type Buffers = OpenglBuffers;
+    type RamImage = RawImage2d<'static, u8>;
+
+    fn new() -> Result<Self> {
+        let events_loop = glium::glutin::EventsLoop::new();
+        let ctx = glium::glutin::ContextBuilder::new()
+            .with_multisampling(0)
+            .build_headless(
+                &events_loop,
+                PhysicalSize {
+                    width: 0.0,
+                    height: 0.0,
+                },
+            )?;
+        let ctx = Rc::new(Headless::new(ctx)?);
+        let dummy_texture = Texture2d::empty_with_format(
+            ctx.as_ref(),
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            1,
+            1,
+        )?;
+
+        Ok(OpenglBackend { ctx, dummy_texture })
+    }
+
+    fn compile_shader(&self, source: ShaderSource) -> Result<Program> {
+        Ok(Program::from_source(
+            self.ctx.as_ref(),
+            source.vertex_glsl,
+            source.fragment_glsl,
+            None,
+        )?)
+    }
+
+    fn build_texture(&self, width: u32, height: u32) -> Result<OpenglTexture> {
+        let color = Texture2dMultisample::empty_with_format(
+            self.ctx.as_ref(),
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+            /*samples=*/ 1,
+        )?;
+        let stencil = DepthStencilRenderBuffer::new(
+            self.ctx.as_ref(),
+            DepthStencilFormat::I24I8,
+            width,
+            height,
+        )?;
+        let texture = OpenglTexture { color, stencil };
+        // A fresh renderbuffer's contents are undefined; clear it up front
+        // rather than leaving the first clipped draw into this texture to
+        // test against whatever bytes happened to be there.
+        self.clear_stencil(&texture)?;
+        Ok(texture)
+    }
+
+    fn build_buffers(&self, vertices: &[GpuVertex], indices: &[u32]) -> Result<OpenglBuffers> {
+        let vertices = VertexBuffer::new(self.ctx.as_ref(), vertices)?;
+        let indices = IndexBuffer::new(self.ctx.as_ref(), PrimitiveType::TrianglesList, indices)?;
+        Ok(OpenglBuffers { vertices, indices })
+    }
+
+    fn draw(
+        &self,
+        texture: &OpenglTexture,
+        buffers: &OpenglBuffers,
+        program: &Program,
+        uniforms: &[(String, BackendUniformValue)],
+        stencil: Option<StencilOp>,
+        pattern: Option<&Texture2d>,
+    ) -> Result<()> {
+        let mut target =
+            SimpleFrameBuffer::with_depth_and_stencil_buffer(self.ctx.as_ref(), &texture.color, &texture.stencil)?;
+
+        let (color_writes, stencil_params) = match stencil {
+            None => (true, Stencil::default()),
+            Some(StencilOp::WriteMask { reference }) => (
+                false,
+                Stencil {
+                    test_clockwise: StencilTest::AlwaysPass,
+                    reference_value_clockwise: reference as i32,
+                    depth_pass_operation_clockwise: StencilOperation::Replace,
+                    test_counter_clockwise: StencilTest::AlwaysPass,
+                    reference_value_counter_clockwise: reference as i32,
+                    depth_pass_operation_counter_clockwise: StencilOperation::Replace,
+                    ..Default::default()
+                },
+            ),
+            Some(StencilOp::TestMask { reference }) => (
+                true,
+                Stencil {
+                    test_clockwise: StencilTest::IfEqual { mask: 0xFF },
+                    reference_value_clockwise: reference as i32,
+                    test_counter_clockwise: StencilTest::IfEqual { mask: 0xFF },
+                    reference_value_counter_clockwise: reference as i32,
+                    ..Default::default()
+                },
+            ),
+        };
+
+        Ok(target.draw(
+            &buffers.vertices,
+            &buffers.indices,
+            program,
+            &ContentUniforms {
+                named: uniforms,
+                pattern: pattern.unwrap_or(&self.dummy_texture),
+            },
+            &DrawParameters {
+                blend: Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::SourceAlpha,
+                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::One,
+                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                    },
+                    constant_value: (0.0, 0.0, 0.0, 0.0),
+                },
+                color_mask: (color_writes, color_writes, color_writes, color_writes),
+                stencil: stencil_params,
+                line_width: Some(1.0),
+                multisampling: false,
+                dithering: false,
+                smooth: None,
+                ..Default::default()
+            },
+        )?)
+    }
+
+    fn clear_stencil(&self, texture: &OpenglTexture) -> Result<()> {
+        let mut target =
+            SimpleFrameBuffer::with_depth_and_stencil_buffer(self.ctx.as_ref(), &texture.color, &texture.stencil)?;
+        target.clear_stencil(0);
+        Ok(())
+    }
+
+    fn read_to_ram(&self, texture: &OpenglTexture) -> Result<RawImage2d<'static, u8>> {
+        Ok(self.resolve(texture)?.read())
+    }
+
+    fn texture_size(&self, texture: &OpenglTexture) -> (u32, u32) {
+        texture.color.dimensions()
+    }
+
+    type SampledTexture = Texture2d;
+
+    fn resolve_to_sampleable(&self, texture: &OpenglTexture) -> Result<Texture2d> {
+        self.resolve(texture)
+    }
+
+    fn draw_fullscreen(
+        &self,
+        target: &OpenglTexture,
+        program: &Program,
+        source: &Texture2d,
+        buffers: &OpenglBuffers,
+        uniforms: &[(String, BackendUniformValue)],
+    ) -> Result<()> {
+        let mut surface = SimpleFrameBuffer::new(self.ctx.as_ref(), &target.color)?;
+        Ok(surface.draw(
+            &buffers.vertices,
+            &buffers.indices,
+            program,
+            &FullscreenUniforms { named: uniforms, source },
+            &DrawParameters::default(),
+        )?)
+    }
+}
+
+impl OpenglBackend {
+    /// Blits `texture`'s (possibly multisampled) color plane into a plain,
+    /// sampleable `Texture2d`, shared by `read_to_ram` and
+    /// `resolve_to_sampleable`.
+    fn resolve(&self, texture: &OpenglTexture) -> Result<Texture2d> {
+        let (width, height) = texture.color.dimensions();
+        let target = Texture2d::empty_with_format(
+            self.ctx.as_ref(),
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?;
+        texture.color.as_surface().blit_color(
+            &glium::Rect {
+                left: 0,
+                bottom: 0,
+                width,
+                height,
+            },
+            &target.as_surface(),
+            &glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: width as i32,
+                height: height as i32,
+            },
+            MagnifySamplerFilter::Linear,
+        );
+
+        Ok(target)
+    }
+}
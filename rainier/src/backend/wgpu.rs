@@ -0,0 +1,823 @@
+//! A wgpu-based headless backend, selected in place of [`super::opengl`] by
+//! the `wgpu` cargo feature. Targets whatever wgpu picks up on the host
+//! (Vulkan/Metal/DX12/GL) instead of requiring a working GLX/EGL driver,
+//! which makes headless rendering viable on machines `opengl` can't touch.
+
+use super::{Backend, ShaderSource, StencilOp, UniformValue as BackendUniformValue};
+use crate::gpu::GpuVertex;
+use crate::Result;
+use std::collections::HashMap;
+use std::mem;
+use wgpu::util::DeviceExt;
+
+/// std140 rounds every array element up to a multiple of 16 bytes,
+/// regardless of the element's own type — the one layout rule that's fixed
+/// by the spec rather than reflected per-shader, so it's the one constant
+/// `pack_uniforms` still hard-codes.
+const ARRAY_STRIDE: usize = 16;
+
+/// A minimum so a stage with no uniforms at all (e.g. `fullscreen.vert`,
+/// which declares none) still gets a non-zero-sized buffer; wgpu rejects
+/// zero-sized buffer allocations.
+const MIN_UNIFORM_BUFFER_SIZE: u32 = 16;
+
+/// One shader stage's compiled module plus the std140 byte layout naga
+/// reflected for its implicit uniform block (the struct naga's GLSL
+/// frontend synthesizes from a stage's loose `uniform` declarations).
+struct StageProgram {
+    module: wgpu::ShaderModule,
+    /// Byte offset of each named uniform within this stage's own uniform
+    /// buffer, read back from naga's IR rather than re-derived by hand, so
+    /// it can't drift from what the generated WGSL actually expects.
+    offsets: HashMap<String, u32>,
+    block_size: u32,
+}
+
+/// The vertex and fragment stages are compiled to two independent
+/// `wgpu::ShaderModule`s (rather than merged into one): each stage's GLSL often declares an entirely different set
+/// of uniforms (e.g. `default.vert` only uses `width`/`height`, while
+/// `default.frag` uses the paint uniforms and never touches `width`), so
+/// naga reflects a different struct per stage. Merging them into one IR
+/// module required rewriting cross-arena handle references to make that
+/// safe, which is a lot of surface for little benefit when wgpu's
+/// `RenderPipelineDescriptor` already accepts separate vertex/fragment
+/// modules directly.
+pub struct WgpuProgram {
+    vertex: StageProgram,
+    fragment: StageProgram,
+}
+
+pub struct WgpuTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    stencil_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+pub struct WgpuBuffers {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    index_count: u32,
+}
+
+pub struct WgpuBackend {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    content_layout: wgpu::BindGroupLayout,
+    fullscreen_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Bound as `patternTex` for draws whose `Paint` isn't a `Pattern`, so
+    /// `default.frag`'s always-declared `patternTex` binding is always
+    /// satisfied, matching `OpenglBackend::dummy_texture`.
+    dummy_texture_view: wgpu::TextureView,
+}
+
+impl Backend for WgpuBackend {
+    type Program = WgpuProgram;
+    type Texture = WgpuTexture;
+    type Buffers = WgpuBuffers;
+    type RamImage = Vec<u8>;
+
+    fn new() -> Result<Self> {
+        pollster::block_on(async {
+            let instance = wgpu::Instance::new(wgpu::Backends::all());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: false,
+                })
+                .await
+                .ok_or("no wgpu adapter available for headless rendering")?;
+
+            // `Rgba32Float` render targets (see `build_texture`) aren't
+            // filterable without this feature, and not every adapter
+            // supports it; request it only if the adapter reports it, and
+            // fall back to nearest-neighbor sampling otherwise rather than
+            // failing bind group creation on every draw.
+            let float32_filterable =
+                adapter.features().contains(wgpu::Features::FLOAT32_FILTERABLE);
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: None,
+                        features: if float32_filterable {
+                            wgpu::Features::FLOAT32_FILTERABLE
+                        } else {
+                            wgpu::Features::empty()
+                        },
+                        limits: wgpu::Limits::default(),
+                    },
+                    None,
+                )
+                .await?;
+
+            let (texture_sample_type, sampler_binding_type, filter_mode) = if float32_filterable {
+                (
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::SamplerBindingType::Filtering,
+                    wgpu::FilterMode::Linear,
+                )
+            } else {
+                (
+                    wgpu::TextureSampleType::Float { filterable: false },
+                    wgpu::SamplerBindingType::NonFiltering,
+                    wgpu::FilterMode::Nearest,
+                )
+            };
+
+            // Every draw binds a vertex uniform buffer, a fragment uniform
+            // buffer, and a `patternTex`/sampler pair, even for non-pattern
+            // paints (see `dummy_texture_view`) and stages with no uniforms
+            // of their own (see `MIN_UNIFORM_BUFFER_SIZE`): the vertex and
+            // fragment stages are compiled (and therefore reflected) as two
+            // separate naga modules, each with its own implicit uniform
+            // block laid out at its own offsets, so they can't share one
+            // binding the way a single merged module's block could.
+            let uniform_binding_entry = |binding: u32, visibility: wgpu::ShaderStages| {
+                wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            };
+            let texture_entry = || wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: texture_sample_type,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            };
+            let sampler_entry = || wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(sampler_binding_type),
+                count: None,
+            };
+            let content_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("rainier content layout"),
+                    entries: &[
+                        uniform_binding_entry(0, wgpu::ShaderStages::VERTEX),
+                        uniform_binding_entry(1, wgpu::ShaderStages::FRAGMENT),
+                        texture_entry(),
+                        sampler_entry(),
+                    ],
+                });
+
+            let fullscreen_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("rainier fullscreen effect layout"),
+                    entries: &[
+                        uniform_binding_entry(0, wgpu::ShaderStages::VERTEX),
+                        uniform_binding_entry(1, wgpu::ShaderStages::FRAGMENT),
+                        texture_entry(),
+                        sampler_entry(),
+                    ],
+                });
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("rainier effect sampler"),
+                mag_filter: filter_mode,
+                min_filter: filter_mode,
+                ..Default::default()
+            });
+
+            let dummy_texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("rainier dummy pattern texture"),
+                size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let dummy_texture_view = dummy_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            Ok(WgpuBackend {
+                device,
+                queue,
+                content_layout,
+                fullscreen_layout,
+                sampler,
+                dummy_texture_view,
+            })
+        })
+    }
+
+    fn compile_shader(&self, source: ShaderSource) -> Result<WgpuProgram> {
+        // naga translates the same GLSL the opengl backend compiles, so the
+        // two backends stay driven off one shader source per effect.
+        let vertex = compile_stage(
+            &self.device,
+            naga::ShaderStage::Vertex,
+            source.vertex_glsl,
+            "rainier vertex shader",
+        )?;
+        let fragment = compile_stage(
+            &self.device,
+            naga::ShaderStage::Fragment,
+            source.fragment_glsl,
+            "rainier fragment shader",
+        )?;
+        Ok(WgpuProgram { vertex, fragment })
+    }
+
+    fn build_texture(&self, width: u32, height: u32) -> Result<WgpuTexture> {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rainier target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let stencil_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rainier stencil"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: STENCIL_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let stencil_view = stencil_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let texture = WgpuTexture {
+            texture,
+            view,
+            stencil_view,
+            width,
+            height,
+        };
+        // A freshly allocated texture's contents are undefined; clear its
+        // stencil plane up front rather than leaving the first clipped draw
+        // into it to test against whatever bytes happened to be there.
+        self.clear_stencil(&texture)?;
+        Ok(texture)
+    }
+
+    fn build_buffers(&self, vertices: &[GpuVertex], indices: &[u32]) -> Result<WgpuBuffers> {
+        let vertices = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier vertices"),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let indices_buf = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier indices"),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+        Ok(WgpuBuffers {
+            vertices,
+            indices: indices_buf,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    fn draw(
+        &self,
+        texture: &WgpuTexture,
+        buffers: &WgpuBuffers,
+        program: &WgpuProgram,
+        uniforms: &[(String, BackendUniformValue)],
+        stencil: Option<StencilOp>,
+        pattern: Option<&wgpu::TextureView>,
+    ) -> Result<()> {
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier vertex uniforms"),
+                contents: &pack_uniforms(&program.vertex.offsets, program.vertex.block_size, uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let fragment_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier fragment uniforms"),
+                contents: &pack_uniforms(&program.fragment.offsets, program.fragment.block_size, uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("rainier content binding"),
+            layout: &self.content_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: fragment_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        pattern.unwrap_or(&self.dummy_texture_view),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let color_writes = !matches!(stencil, Some(StencilOp::WriteMask { .. }));
+        let (stencil_state, reference) = stencil_pipeline_state(stencil);
+
+        let pipeline_layout =
+            self.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("rainier pipeline layout"),
+                    bind_group_layouts: &[&self.content_layout],
+                    push_constant_ranges: &[],
+                });
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("rainier pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &program.vertex.module,
+                    entry_point: "main",
+                    buffers: &[GpuVertex::layout()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &program.fragment.module,
+                    entry_point: "main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: if color_writes {
+                            wgpu::ColorWrites::ALL
+                        } else {
+                            wgpu::ColorWrites::empty()
+                        },
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: STENCIL_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: stencil_state,
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("rainier draw"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &texture.stencil_view,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                }),
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_stencil_reference(reference);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_vertex_buffer(0, buffers.vertices.slice(..));
+            pass.set_index_buffer(buffers.indices.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..buffers.index_count, 0, 0..1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+
+    fn clear_stencil(&self, texture: &WgpuTexture) -> Result<()> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("rainier clear stencil"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &texture.stencil_view,
+                depth_ops: None,
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+        });
+        self.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+
+    fn read_to_ram(&self, texture: &WgpuTexture) -> Result<Vec<u8>> {
+        let bytes_per_row = texture.width * 16;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rainier readback"),
+            size: (bytes_per_row * texture.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: texture.width,
+                height: texture.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        // The target is `Rgba32Float`; convert each channel to RGBA8.
+        let rgba8 = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .map(|v| (v.max(0.0).min(1.0) * 255.0).round() as u8)
+            .collect();
+        Ok(rgba8)
+    }
+
+    fn texture_size(&self, texture: &WgpuTexture) -> (u32, u32) {
+        (texture.width, texture.height)
+    }
+
+    type SampledTexture = wgpu::TextureView;
+
+    fn resolve_to_sampleable(&self, texture: &WgpuTexture) -> Result<wgpu::TextureView> {
+        // The target texture already carries TEXTURE_BINDING usage, so a
+        // fresh view is enough; no separate resolve/copy is needed since
+        // `build_texture` never creates a multisampled render target.
+        Ok(texture.texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    fn draw_fullscreen(
+        &self,
+        target: &WgpuTexture,
+        program: &WgpuProgram,
+        source: &wgpu::TextureView,
+        buffers: &WgpuBuffers,
+        uniforms: &[(String, BackendUniformValue)],
+    ) -> Result<()> {
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier effect vertex uniforms"),
+                contents: &pack_uniforms(&program.vertex.offsets, program.vertex.block_size, uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let fragment_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rainier effect fragment uniforms"),
+                contents: &pack_uniforms(&program.fragment.offsets, program.fragment.block_size, uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("rainier effect binding"),
+            layout: &self.fullscreen_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: fragment_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout =
+            self.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("rainier effect pipeline layout"),
+                    bind_group_layouts: &[&self.fullscreen_layout],
+                    push_constant_ranges: &[],
+                });
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("rainier effect pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &program.vertex.module,
+                    entry_point: "main",
+                    buffers: &[GpuVertex::layout()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &program.fragment.module,
+                    entry_point: "main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("rainier effect pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_vertex_buffer(0, buffers.vertices.slice(..));
+            pass.set_index_buffer(buffers.indices.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..buffers.index_count, 0, 0..1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+fn stencil_pipeline_state(stencil: Option<StencilOp>) -> (wgpu::StencilState, u32) {
+    match stencil {
+        None => (wgpu::StencilState::default(), 0),
+        Some(StencilOp::WriteMask { reference }) => {
+            let face = wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Replace,
+            };
+            (
+                wgpu::StencilState {
+                    front: face,
+                    back: face,
+                    read_mask: 0xFF,
+                    write_mask: 0xFF,
+                },
+                reference as u32,
+            )
+        }
+        Some(StencilOp::TestMask { reference }) => {
+            let face = wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Equal,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Keep,
+            };
+            (
+                wgpu::StencilState {
+                    front: face,
+                    back: face,
+                    read_mask: 0xFF,
+                    write_mask: 0x00,
+                },
+                reference as u32,
+            )
+        }
+    }
+}
+
+/// Parses one GLSL stage, reflects its implicit uniform block's std140
+/// layout, validates, and compiles it to its own `wgpu::ShaderModule`.
+fn compile_stage(
+    device: &wgpu::Device,
+    stage: naga::ShaderStage,
+    glsl: &'static str,
+    label: &'static str,
+) -> Result<StageProgram> {
+    let module = naga::front::glsl::Parser::default()
+        .parse(&naga::front::glsl::Options::from(stage), glsl)?;
+    let (offsets, block_size) = reflect_uniform_block(&module);
+
+    let module_info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)?;
+    let wgsl = naga::back::wgsl::write_string(&module, &module_info, naga::back::wgsl::WriterFlags::empty())?;
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+    Ok(StageProgram { module, offsets, block_size })
+}
+
+/// Finds the single implicit uniform-block global naga's GLSL frontend
+/// synthesizes for a stage's loose `uniform` declarations (a stage with
+/// none, like `fullscreen.vert`, has no such global) and reads back the
+/// std140 byte offset naga already assigned each named member, plus the
+/// block's total size. Reading these back, rather than re-deriving std140
+/// alignment/offset rules by hand, means the layout `pack_uniforms` writes
+/// to can't drift from what the generated WGSL actually declares.
+fn reflect_uniform_block(module: &naga::Module) -> (HashMap<String, u32>, u32) {
+    for (_, global) in module.global_variables.iter() {
+        if global.space != naga::AddressSpace::Uniform {
+            continue;
+        }
+        if let naga::TypeInner::Struct { members, span } = &module.types[global.ty].inner {
+            let offsets = members
+                .iter()
+                .filter_map(|member| member.name.as_ref().map(|name| (name.clone(), member.offset)))
+                .collect();
+            return (offsets, *span);
+        }
+    }
+    (HashMap::new(), MIN_UNIFORM_BUFFER_SIZE)
+}
+
+/// Splits an array-element uniform name like `"gradStopPos[3]"` into
+/// (`"gradStopPos"`, `3`); `None` for a plain, non-indexed uniform name.
+fn array_index(name: &str) -> Option<(&str, usize)> {
+    let open = name.find('[')?;
+    let index = name.strip_suffix(']')?[open + 1..].parse().ok()?;
+    Some((&name[..open], index))
+}
+
+/// Packs `uniforms` into a byte buffer matching `offsets`' std140 layout
+/// (reflected from naga's own IR, see [`reflect_uniform_block`]), at least
+/// `block_size` bytes. A uniform absent from `offsets` simply isn't
+/// referenced by this particular stage's shader (e.g. the vertex stage
+/// never reads `paintKind`) and is skipped, rather than e.g. the fragment
+/// stage's values landing at whatever offset the vertex stage's uniforms
+/// happened to occupy. `name[i]`-style array elements are resolved against
+/// their base member's reflected offset plus `i * ARRAY_STRIDE`.
+fn pack_uniforms(offsets: &HashMap<String, u32>, block_size: u32, uniforms: &[(String, BackendUniformValue)]) -> Vec<u8> {
+    let mut bytes = vec![0u8; (block_size as usize).max(MIN_UNIFORM_BUFFER_SIZE as usize)];
+
+    let write = |bytes: &mut Vec<u8>, offset: usize, packed: &[f32]| {
+        let end = offset + packed.len() * mem::size_of::<f32>();
+        if end > bytes.len() {
+            bytes.resize(end, 0);
+        }
+        bytes[offset..end].copy_from_slice(bytemuck::cast_slice(packed));
+    };
+
+    for (name, value) in uniforms {
+        let offset = match array_index(name) {
+            Some((base, index)) => match offsets.get(base) {
+                Some(&base_offset) => base_offset as usize + index * ARRAY_STRIDE,
+                None => continue,
+            },
+            None => match offsets.get(name.as_str()) {
+                Some(&offset) => offset as usize,
+                None => continue,
+            },
+        };
+        match *value {
+            BackendUniformValue::Float(v) => write(&mut bytes, offset, &[v]),
+            BackendUniformValue::Vec2(v) => write(&mut bytes, offset, &v),
+            BackendUniformValue::Vec4(v) => write(&mut bytes, offset, &v),
+            BackendUniformValue::Mat3(columns) => {
+                for (i, column) in columns.iter().enumerate() {
+                    write(&mut bytes, offset + i * ARRAY_STRIDE, &column[..]);
+                }
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_named_uniforms_at_their_reflected_offsets() {
+        // Mirrors default.frag's `uniform float paintKind; uniform vec2
+        // gradFrom;`: std140 packs gradFrom at offset 8 (its own 8-byte
+        // alignment), not 16 (one-slot-per-uniform would wrongly assume).
+        let offsets = HashMap::from([("paintKind".to_string(), 0), ("gradFrom".to_string(), 8)]);
+        let uniforms = vec![
+            ("paintKind".to_string(), BackendUniformValue::Float(3.0)),
+            ("gradFrom".to_string(), BackendUniformValue::Vec2([1.0, 2.0])),
+        ];
+        let bytes = pack_uniforms(&offsets, 16, &uniforms);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[0..4]), &[3.0]);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[8..16]), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn skips_uniforms_the_stage_does_not_reference() {
+        // default.vert's offsets map only has width/height; paintKind (a
+        // fragment-only uniform) must not land in the vertex buffer at all.
+        let offsets = HashMap::from([("width".to_string(), 0)]);
+        let uniforms = vec![
+            ("width".to_string(), BackendUniformValue::Float(640.0)),
+            ("paintKind".to_string(), BackendUniformValue::Float(1.0)),
+        ];
+        let bytes = pack_uniforms(&offsets, 16, &uniforms);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[0..4]), &[640.0]);
+    }
+
+    #[test]
+    fn array_elements_resolve_against_their_base_offset() {
+        let offsets = HashMap::from([("gradStopPos".to_string(), 32)]);
+        let uniforms = vec![
+            ("gradStopPos[0]".to_string(), BackendUniformValue::Float(0.25)),
+            ("gradStopPos[2]".to_string(), BackendUniformValue::Float(0.75)),
+        ];
+        let bytes = pack_uniforms(&offsets, 32 + 3 * ARRAY_STRIDE as u32, &uniforms);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[32..36]), &[0.25]);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[32 + 2 * ARRAY_STRIDE..32 + 2 * ARRAY_STRIDE + 4]), &[0.75]);
+    }
+
+    #[test]
+    fn mat3_writes_three_vec4_aligned_columns() {
+        let offsets = HashMap::from([("patternTransform".to_string(), 0)]);
+        let uniforms = vec![(
+            "patternTransform".to_string(),
+            BackendUniformValue::Mat3([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]),
+        )];
+        let bytes = pack_uniforms(&offsets, 3 * ARRAY_STRIDE as u32, &uniforms);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[0..12]), &[1.0, 2.0, 3.0]);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[16..28]), &[4.0, 5.0, 6.0]);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&bytes[32..44]), &[7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn array_index_parses_indexed_names() {
+        assert_eq!(array_index("gradStopPos[3]"), Some(("gradStopPos", 3)));
+        assert_eq!(array_index("paintKind"), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Full-frame post-processing, run after `Gpu::precompose` has resolved a
+//! composition's elements into a single texture.
+
+use crate::{
+    backend::{Backend, UniformValue},
+    gpu::{Gpu, GpuVertex, UniformBuffer},
+    Result,
+};
+use std::rc::Rc;
+
+const FULLSCREEN_VERTEX_SHADER: &str = include_str!("shaders/fullscreen.vert");
+const COLOR_MATRIX_FRAGMENT_SHADER: &str = include_str!("shaders/color_matrix.frag");
+const BLUR_FRAGMENT_SHADER: &str = include_str!("shaders/blur.frag");
+
+/// A screen-space effect applied to a precomposed frame.
+#[derive(Debug, Clone)]
+pub enum Effect {
+    /// A 4x5 row-major matrix (`[r, g, b, a, bias]` per output channel)
+    /// applied to every pixel's RGBA, covering saturation/hue/sepia/invert
+    /// style tonal grading.
+    ColorMatrix([f32; 20]),
+    /// A separable Gaussian blur, run as a horizontal pass followed by a
+    /// vertical one.
+    Blur { radius: f32 },
+}
+
+impl<B: Backend> Gpu<B> {
+    /// Runs `effects` in order over `texture`, returning the final result.
+    /// Each effect resolves the previous pass's output to a sampleable
+    /// texture and draws a fullscreen quad with an effect-specific
+    /// fragment shader.
+    pub fn apply_effects(&self, texture: Rc<B::Texture>, effects: &[Effect]) -> Result<Rc<B::Texture>> {
+        let mut current = texture;
+        for effect in effects {
+            current = self.apply_effect(&current, effect)?;
+        }
+        Ok(current)
+    }
+
+    fn apply_effect(&self, source: &B::Texture, effect: &Effect) -> Result<Rc<B::Texture>> {
+        match effect {
+            Effect::ColorMatrix(matrix) => {
+                let mut uniforms = UniformBuffer::default();
+                push_color_matrix_uniforms(&mut uniforms, matrix);
+                self.run_fullscreen_pass(source, COLOR_MATRIX_FRAGMENT_SHADER, uniforms)
+            }
+            Effect::Blur { radius } => {
+                let mut horizontal = UniformBuffer::default();
+                horizontal.push(String::from("direction"), UniformValue::Vec2([1.0, 0.0]));
+                horizontal.push(String::from("radius"), UniformValue::Float(*radius));
+                let pass1 = self.run_fullscreen_pass(source, BLUR_FRAGMENT_SHADER, horizontal)?;
+
+                let mut vertical = UniformBuffer::default();
+                vertical.push(String::from("direction"), UniformValue::Vec2([0.0, 1.0]));
+                vertical.push(String::from("radius"), UniformValue::Float(*radius));
+                self.run_fullscreen_pass(&pass1, BLUR_FRAGMENT_SHADER, vertical)
+            }
+        }
+    }
+
+    fn run_fullscreen_pass(
+        &self,
+        source: &B::Texture,
+        fragment_glsl: &str,
+        mut uniforms: UniformBuffer,
+    ) -> Result<Rc<B::Texture>> {
+        let (width, height) = self.backend().texture_size(source);
+        uniforms.push(String::from("width"), UniformValue::Float(width as f32));
+        uniforms.push(String::from("height"), UniformValue::Float(height as f32));
+
+        let program = self.backend().compile_shader(crate::backend::ShaderSource {
+            vertex_glsl: FULLSCREEN_VERTEX_SHADER,
+            fragment_glsl,
+        })?;
+        let sampled = self.backend().resolve_to_sampleable(source)?;
+        let quad = self.backend().build_buffers(&FULLSCREEN_QUAD, &FULLSCREEN_QUAD_INDICES)?;
+
+        let target = self.build_texture(width, height)?;
+        self.backend()
+            .draw_fullscreen(&target, &program, &sampled, &quad, uniforms.as_slice())?;
+        Ok(Rc::new(target))
+    }
+}
+
+const FULLSCREEN_QUAD: [GpuVertex; 4] = [
+    GpuVertex { vpos: [-1.0, -1.0], vcol: [1.0, 1.0, 1.0, 1.0] },
+    GpuVertex { vpos: [1.0, -1.0], vcol: [1.0, 1.0, 1.0, 1.0] },
+    GpuVertex { vpos: [1.0, 1.0], vcol: [1.0, 1.0, 1.0, 1.0] },
+    GpuVertex { vpos: [-1.0, 1.0], vcol: [1.0, 1.0, 1.0, 1.0] },
+];
+const FULLSCREEN_QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+fn push_color_matrix_uniforms(uniforms: &mut UniformBuffer, matrix: &[f32; 20]) {
+    for (row, name) in ["m0", "m1", "m2", "m3"].iter().enumerate() {
+        let base = row * 5;
+        uniforms.push(
+            String::from(*name),
+            UniformValue::Vec4([matrix[base], matrix[base + 1], matrix[base + 2], matrix[base + 3]]),
+        );
+    }
+    uniforms.push(
+        String::from("bias"),
+        UniformValue::Vec4([matrix[4], matrix[9], matrix[14], matrix[19]]),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn as_vec4(value: &UniformValue) -> [f32; 4] {
+        match value {
+            UniformValue::Vec4(v) => *v,
+            other => panic!("expected Vec4, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_color_matrix_uniforms_splits_rows_and_gathers_bias() {
+        // Row-major 4x5: each row's first 4 entries are that row's `mN`
+        // uniform, and the 5th (index 4 of each row) is gathered separately
+        // into `bias`, not left inline with its row.
+        let matrix: [f32; 20] = [
+            0.0, 1.0, 2.0, 3.0, 4.0, //
+            5.0, 6.0, 7.0, 8.0, 9.0, //
+            10.0, 11.0, 12.0, 13.0, 14.0, //
+            15.0, 16.0, 17.0, 18.0, 19.0,
+        ];
+        let mut uniforms = UniformBuffer::default();
+        push_color_matrix_uniforms(&mut uniforms, &matrix);
+
+        let pushed = uniforms.as_slice();
+        assert_eq!(pushed.len(), 5);
+        assert_eq!(pushed[0].0, "m0");
+        assert_eq!(as_vec4(&pushed[0].1), [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(pushed[1].0, "m1");
+        assert_eq!(as_vec4(&pushed[1].1), [5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(pushed[2].0, "m2");
+        assert_eq!(as_vec4(&pushed[2].1), [10.0, 11.0, 12.0, 13.0]);
+        assert_eq!(pushed[3].0, "m3");
+        assert_eq!(as_vec4(&pushed[3].1), [15.0, 16.0, 17.0, 18.0]);
+        assert_eq!(pushed[4].0, "bias");
+        assert_eq!(as_vec4(&pushed[4].1), [4.0, 9.0, 14.0, 19.0]);
+    }
+}
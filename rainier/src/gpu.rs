@@ -1,31 +1,11 @@
 use crate::{
+    backend::{Backend, DefaultBackend, ShaderSource, StencilOp, UniformValue},
+    paint::Paint,
     raster::{raster_path, Method},
     Result,
     SampleDepth,
     V4,
 };
-use glium::{
-    backend::glutin::headless::Headless,
-    implement_vertex,
-    index::PrimitiveType,
-    texture::{
-        texture2d::Texture2d,
-        texture2d_multisample::Texture2dMultisample,
-        MipmapsOption,
-        RawImage2d,
-        UncompressedFloatFormat,
-    },
-    uniforms::{MagnifySamplerFilter, UniformValue, Uniforms},
-    Blend,
-    BlendingFunction,
-    DrawParameters,
-    IndexBuffer,
-    LinearBlendingFactor,
-    Program,
-    Surface,
-    VertexBuffer,
-};
-use glutin::dpi::PhysicalSize;
 use itertools::Itertools;
 use lyon_path::Builder;
 use rand::random;
@@ -37,84 +17,96 @@ pub struct GpuVertex {
     pub vcol: [f32; 4],
 }
 
-implement_vertex!(GpuVertex, vpos, vcol);
+#[cfg(feature = "opengl")]
+glium::implement_vertex!(GpuVertex, vpos, vcol);
+
+#[cfg(feature = "wgpu")]
+unsafe impl bytemuck::Pod for GpuVertex {}
+#[cfg(feature = "wgpu")]
+unsafe impl bytemuck::Zeroable for GpuVertex {}
+
+#[cfg(feature = "wgpu")]
+impl GpuVertex {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GpuVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+        }
+    }
+}
 
 pub const VERTEX_SHADER: &str = include_str!("shaders/default.vert");
 const FRAGMENT_SHADER: &str = include_str!("shaders/default.frag");
 
-#[derive(Clone)]
-pub struct Shader {
+pub struct Shader<B: Backend> {
     id: u64,
-    program: Rc<Program>,
+    program: Rc<B::Program>,
     uniforms: UniformBuffer,
 }
 
+impl<B: Backend> Clone for Shader<B> {
+    fn clone(&self) -> Self {
+        Shader {
+            id: self.id,
+            program: self.program.clone(),
+            uniforms: self.uniforms.clone(),
+        }
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct UniformBuffer {
-    uniforms: Vec<(String, UniformValue<'static>)>,
+    uniforms: Vec<(String, UniformValue)>,
 }
 
 impl UniformBuffer {
-    pub fn push(&mut self, name: String, value: UniformValue<'static>) {
+    pub fn push(&mut self, name: String, value: UniformValue) {
         self.uniforms.push((name, value));
     }
-}
 
-impl Uniforms for UniformBuffer {
-    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
-        for (name, value) in &self.uniforms {
-            f(name.as_str(), *value);
-        }
+    pub(crate) fn as_slice(&self) -> &[(String, UniformValue)] {
+        &self.uniforms
     }
 }
 
 /// A rasterable element in a composition.
-pub struct Element {
+pub struct Element<B: Backend> {
     pub path: Builder,
-    pub color: V4,
+    pub paint: Paint<B>,
     pub raster_method: Method,
-    pub shader: Shader,
+    pub shader: Shader<B>,
     pub sample_depth: SampleDepth,
+    /// An optional mask: only the parts of this element covered by `clip`
+    /// are drawn. Implemented with a stencil pass, so an element with a
+    /// clip is drawn on its own rather than batched with its shader's other
+    /// elements.
+    pub clip: Option<Builder>,
 }
 
-pub struct Gpu {
-    ctx: Rc<Headless>,
-    program: Rc<Program>,
-}
+/// The stencil reference clip masks are written to and tested against.
+/// Elements only carry a single `clip`, so depth is always 1 for now;
+/// nesting clips (a clip that is itself clipped) would need a higher
+/// reference per level so inner and outer masks don't collide.
+const CLIP_STENCIL_REFERENCE: u8 = 1;
 
-pub struct GpuCommand<'a> {
-    pub vertices: VertexBuffer<GpuVertex>,
-    pub indices: IndexBuffer<u32>,
-    pub texture: &'a Texture2dMultisample,
-    pub program: &'a Program,
-    pub uniforms: &'a UniformBuffer,
+pub struct Gpu<B: Backend = DefaultBackend> {
+    backend: B,
+    program: Rc<B::Program>,
 }
 
-impl Gpu {
+impl<B: Backend> Gpu<B> {
     pub fn new() -> Result<Self> {
-        let events_loop = glium::glutin::EventsLoop::new();
-        let ctx = glium::glutin::ContextBuilder::new()
-            .with_multisampling(0)
-            .build_headless(
-                &events_loop,
-                PhysicalSize {
-                    width: 0.0,
-                    height: 0.0,
-                },
-            )?;
-        let ctx = Rc::new(Headless::new(ctx)?);
-
-        let program = Rc::new(Program::from_source(
-            ctx.as_ref(),
-            VERTEX_SHADER,
-            FRAGMENT_SHADER,
-            None,
-        )?);
+        let backend = B::new()?;
+        let program = Rc::new(backend.compile_shader(ShaderSource {
+            vertex_glsl: VERTEX_SHADER,
+            fragment_glsl: FRAGMENT_SHADER,
+        })?);
 
-        Ok(Gpu { program, ctx })
+        Ok(Gpu { backend, program })
     }
 
-    pub fn default_shader(&self, width: f32, height: f32) -> Shader {
+    pub fn default_shader(&self, width: f32, height: f32) -> Shader<B> {
         Shader {
             id: random(),
             program: self.program.clone(),
@@ -127,16 +119,14 @@ impl Gpu {
         }
     }
 
-    pub fn compile_glsl(&self, source: &str) -> Result<Rc<Program>> {
-        Ok(Rc::new(Program::from_source(
-            self.ctx.as_ref(),
-            VERTEX_SHADER,
-            source,
-            None,
-        )?))
+    pub fn compile_glsl(&self, source: &str) -> Result<Rc<B::Program>> {
+        Ok(Rc::new(self.backend.compile_shader(ShaderSource {
+            vertex_glsl: VERTEX_SHADER,
+            fragment_glsl: source,
+        })?))
     }
 
-    pub fn build_shader(&self, program: Rc<Program>, uniforms: UniformBuffer) -> Result<Shader> {
+    pub fn build_shader(&self, program: Rc<B::Program>, uniforms: UniformBuffer) -> Result<Shader<B>> {
         Ok(Shader {
             id: random(),
             program,
@@ -144,136 +134,130 @@ impl Gpu {
         })
     }
 
-    pub fn build_texture(&self, width: u32, height: u32) -> Result<Texture2dMultisample> {
-        Ok(Texture2dMultisample::empty_with_format(
-            self.ctx.as_ref(),
-            UncompressedFloatFormat::F32F32F32F32,
-            MipmapsOption::NoMipmap,
-            width,
-            height,
-            /*samples=*/ 1,
-        )?)
+    pub fn build_texture(&self, width: u32, height: u32) -> Result<B::Texture> {
+        self.backend.build_texture(width, height)
     }
 
-    pub fn build_ram_texture(&self, width: u32, height: u32) -> Result<Texture2d> {
-        Ok(Texture2d::empty_with_format(
-            self.ctx.as_ref(),
-            UncompressedFloatFormat::F32F32F32F32,
-            MipmapsOption::NoMipmap,
-            width,
-            height,
-        )?)
+    pub(crate) fn backend(&self) -> &B {
+        &self.backend
     }
 
     pub fn precompose(
         &self,
         width: u32,
         height: u32,
-        elements: impl Iterator<Item = Element>,
-    ) -> Result<Rc<Texture2dMultisample>> {
+        elements: impl Iterator<Item = Element<B>>,
+    ) -> Result<Rc<B::Texture>> {
         let texture = self.build_texture(width, height)?;
         for (_id, batch) in &elements.group_by(|e| e.shader.id) {
-            let mut batch = batch.peekable();
-            let mut first = if let Some(first) = batch.peek() {
+            let batch: Vec<Element<B>> = batch.collect();
+            let mut first = if let Some(first) = batch.first() {
                 first.shader.clone()
             } else {
                 println!("This is possible??");
                 continue;
             };
 
-            // TODO: reconcile conflicts between user uniforms and the defaults
+            // TODO: reconcile conflicts between user uniforms and the defaults,
+            // and between the paints of batched elements that share a shader.
             first
                 .uniforms
                 .push(String::from("width"), UniformValue::Float(width as f32));
             first
                 .uniforms
                 .push(String::from("height"), UniformValue::Float(height as f32));
+            batch[0].paint.push_uniforms(&mut first.uniforms);
 
-            let (indices, vertices) = self.build_buffers(batch)?;
-            self.draw_to_texture(GpuCommand {
-                indices,
-                vertices,
-                texture: &texture,
-                program: first.program.as_ref(),
-                uniforms: &first.uniforms,
-            })?;
+            if batch.iter().any(|element| element.clip.is_some()) {
+                // Clipped elements need their own stencil write/test passes,
+                // so they can't share the single draw call the fast path
+                // below batches the rest of the shader's elements into.
+                for element in batch {
+                    self.draw_element(&texture, first.program.as_ref(), &first.uniforms, element)?;
+                }
+            } else {
+                // Only the first element's pattern (if any) is bound, same
+                // limitation as the paint uniforms pushed above: batched
+                // elements that share a shader also share one draw call.
+                let pattern = batch[0].paint.pattern_texture().map(Rc::as_ref);
+                let buffers = self.build_buffers(batch.into_iter())?;
+                self.backend.draw(
+                    &texture,
+                    &buffers,
+                    first.program.as_ref(),
+                    first.uniforms.as_slice(),
+                    None,
+                    pattern,
+                )?;
+            }
         }
         Ok(Rc::new(texture))
     }
 
-    pub fn read_to_ram(&self, texture: &Texture2dMultisample) -> Result<RawImage2d<u8>> {
-        let (width, height) = texture.dimensions();
-        let target = self.build_ram_texture(width, height)?;
-        texture.as_surface().blit_color(
-            &glium::Rect {
-                left: 0,
-                bottom: 0,
-                width,
-                height,
-            },
-            &target.as_surface(),
-            &glium::BlitTarget {
-                left: 0,
-                bottom: 0,
-                width: width as i32,
-                height: height as i32,
-            },
-            MagnifySamplerFilter::Linear,
-        );
+    fn draw_element(
+        &self,
+        texture: &B::Texture,
+        program: &B::Program,
+        uniforms: &UniformBuffer,
+        element: Element<B>,
+    ) -> Result<()> {
+        let clip = element.clip;
+        let pattern = element.paint.pattern_texture().map(Rc::as_ref);
+        let color = element.paint.base_color();
+        let (vertices, indices) = raster_path(element.path, element.raster_method, color)?;
+        let content_buffers = self.backend.build_buffers(vertices.as_slice(), indices.as_slice())?;
 
-        Ok(target.read())
+        match clip {
+            None => {
+                self.backend
+                    .draw(texture, &content_buffers, program, uniforms.as_slice(), None, pattern)?;
+            }
+            Some(clip) => {
+                let (clip_vertices, clip_indices) =
+                    raster_path(clip, Method::Fill, V4::new(0.0, 0.0, 0.0, 0.0))?;
+                let clip_buffers = self
+                    .backend
+                    .build_buffers(clip_vertices.as_slice(), clip_indices.as_slice())?;
+                self.backend.draw(
+                    texture,
+                    &clip_buffers,
+                    program,
+                    uniforms.as_slice(),
+                    Some(StencilOp::WriteMask { reference: CLIP_STENCIL_REFERENCE }),
+                    None,
+                )?;
+                self.backend.draw(
+                    texture,
+                    &content_buffers,
+                    program,
+                    uniforms.as_slice(),
+                    Some(StencilOp::TestMask { reference: CLIP_STENCIL_REFERENCE }),
+                    pattern,
+                )?;
+                self.backend.clear_stencil(texture)?;
+            }
+        }
+        Ok(())
     }
 
-    fn draw_to_texture(&self, cmd: GpuCommand) -> Result<()> {
-        Ok(cmd.texture.as_surface().draw(
-            &cmd.vertices,
-            &cmd.indices,
-            cmd.program,
-            cmd.uniforms,
-            &DrawParameters {
-                blend: Blend {
-                    color: BlendingFunction::Addition {
-                        source: LinearBlendingFactor::SourceAlpha,
-                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
-                    },
-                    alpha: BlendingFunction::Addition {
-                        source: LinearBlendingFactor::One,
-                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
-                    },
-                    constant_value: (0.0, 0.0, 0.0, 0.0),
-                },
-                line_width: Some(1.0),
-                multisampling: false,
-                dithering: false,
-                smooth: None,
-                ..Default::default()
-            },
-        )?)
+    pub fn read_to_ram(&self, texture: &B::Texture) -> Result<B::RamImage> {
+        self.backend.read_to_ram(texture)
     }
 
-    fn build_buffers(
-        &self,
-        mut elements: impl Iterator<Item = Element>,
-    ) -> Result<(IndexBuffer<u32>, VertexBuffer<GpuVertex>)> {
+    fn build_buffers(&self, mut elements: impl Iterator<Item = Element<B>>) -> Result<B::Buffers> {
         let (_, vertices, indices) = elements
             .try_fold::<_, _, Result<(u32, Vec<GpuVertex>, Vec<u32>)>>(
                 (0, vec![], vec![]),
                 |(idx, mut vertices, mut indices), element| {
+                    let color = element.paint.base_color();
                     let (mut new_vertices, new_indices) =
-                        raster_path(element.path, element.raster_method, element.color)?;
+                        raster_path(element.path, element.raster_method, color)?;
                     vertices.append(&mut new_vertices);
                     indices.extend(new_indices.into_iter().map(|i| i + idx));
                     Ok((vertices.len() as u32, vertices, indices))
                 },
             )?;
 
-        let vertex_buffer = VertexBuffer::new(self.ctx.as_ref(), vertices.as_slice())?;
-        let index_buffer = IndexBuffer::new(
-            self.ctx.as_ref(),
-            PrimitiveType::TrianglesList,
-            indices.as_slice(),
-        )?;
-
-        Ok((index_buffer, vertex_buffer))
+        self.backend.build_buffers(vertices.as_slice(), indices.as_slice())
     }
 }
@@ -0,0 +1,242 @@
+//! Fill styles for composition elements.
+
+use crate::gpu::UniformBuffer;
+use crate::{
+    backend::{Backend, UniformValue},
+    Point,
+    V4,
+};
+use std::rc::Rc;
+
+/// Shader-side uniforms only reserve room for this many stops; gradients
+/// with more are truncated rather than growing the uniform count per draw
+/// call unboundedly.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+const PAINT_KIND_SOLID: f32 = 0.0;
+const PAINT_KIND_LINEAR: f32 = 1.0;
+const PAINT_KIND_RADIAL: f32 = 2.0;
+const PAINT_KIND_PATTERN: f32 = 3.0;
+
+/// How out-of-`[0, 1]` pattern UVs are handled.
+#[derive(Debug, Clone, Copy)]
+pub enum RepeatMode {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl RepeatMode {
+    fn as_uniform(self) -> f32 {
+        match self {
+            RepeatMode::Clamp => 0.0,
+            RepeatMode::Repeat => 1.0,
+            RepeatMode::Mirror => 2.0,
+        }
+    }
+}
+
+/// How an `Element`'s path interior is colored.
+pub enum Paint<B: Backend> {
+    Solid(V4),
+    LinearGradient {
+        from: Point,
+        to: Point,
+        stops: Vec<(f32, V4)>,
+    },
+    RadialGradient {
+        center: Point,
+        radius: f32,
+        stops: Vec<(f32, V4)>,
+    },
+    /// Fills the path by sampling `texture` instead of interpolating a
+    /// color. `transform` maps pattern-local UV space (the unit square) to
+    /// world space, matching the convention `Gpu::precompose` already draws
+    /// elements in; `push_uniforms` inverts it once so the fragment shader
+    /// only has to do a forward multiply by world position.
+    Pattern {
+        texture: Rc<B::SampledTexture>,
+        transform: [[f32; 3]; 3],
+        repeat: RepeatMode,
+    },
+}
+
+// A derived `Clone` would add an unwanted `B: Clone` bound; only `texture`
+// (an `Rc`) needs cloning support from `B` at all, and `Rc<T>: Clone`
+// regardless of `T`.
+impl<B: Backend> Clone for Paint<B> {
+    fn clone(&self) -> Self {
+        match self {
+            Paint::Solid(color) => Paint::Solid(*color),
+            Paint::LinearGradient { from, to, stops } => Paint::LinearGradient {
+                from: *from,
+                to: *to,
+                stops: stops.clone(),
+            },
+            Paint::RadialGradient { center, radius, stops } => Paint::RadialGradient {
+                center: *center,
+                radius: *radius,
+                stops: stops.clone(),
+            },
+            Paint::Pattern { texture, transform, repeat } => Paint::Pattern {
+                texture: texture.clone(),
+                transform: *transform,
+                repeat: *repeat,
+            },
+        }
+    }
+}
+
+impl<B: Backend> Paint<B> {
+    /// The color `raster_path` bakes into `vcol`. For gradients and
+    /// patterns this is only a fallback for backends/draw paths that ignore
+    /// the uniforms below; `default.frag` overrides it per-pixel.
+    pub fn base_color(&self) -> V4 {
+        match self {
+            Paint::Solid(color) => *color,
+            Paint::LinearGradient { stops, .. } | Paint::RadialGradient { stops, .. } => stops
+                .first()
+                .map(|(_, color)| *color)
+                .unwrap_or(V4::new(1.0, 1.0, 1.0, 1.0)),
+            Paint::Pattern { .. } => V4::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+
+    /// The texture a `Pattern` paint samples, so the draw call can bind it
+    /// as `patternTex` alongside the uniforms `push_uniforms` pushes here.
+    /// `UniformBuffer` has no way to carry a backend texture handle itself
+    /// (see `Backend::draw`'s `pattern` parameter).
+    pub fn pattern_texture(&self) -> Option<&Rc<B::SampledTexture>> {
+        match self {
+            Paint::Pattern { texture, .. } => Some(texture),
+            _ => None,
+        }
+    }
+
+    /// Push this paint's parameters as named uniforms so `default.frag` can
+    /// evaluate the gradient or pattern per-pixel from the interpolated
+    /// world position `default.vert` forwards.
+    pub fn push_uniforms(&self, uniforms: &mut UniformBuffer) {
+        match self {
+            Paint::Solid(_) => uniforms.push(String::from("paintKind"), UniformValue::Float(PAINT_KIND_SOLID)),
+            Paint::LinearGradient { from, to, stops } => {
+                uniforms.push(String::from("paintKind"), UniformValue::Float(PAINT_KIND_LINEAR));
+                uniforms.push(String::from("gradFrom"), UniformValue::Vec2([from.x, from.y]));
+                uniforms.push(String::from("gradTo"), UniformValue::Vec2([to.x, to.y]));
+                push_stops(uniforms, stops);
+            }
+            Paint::RadialGradient { center, radius, stops } => {
+                uniforms.push(String::from("paintKind"), UniformValue::Float(PAINT_KIND_RADIAL));
+                uniforms.push(String::from("gradFrom"), UniformValue::Vec2([center.x, center.y]));
+                uniforms.push(String::from("gradRadius"), UniformValue::Float(*radius));
+                push_stops(uniforms, stops);
+            }
+            Paint::Pattern { transform, repeat, .. } => {
+                uniforms.push(String::from("paintKind"), UniformValue::Float(PAINT_KIND_PATTERN));
+                uniforms.push(
+                    String::from("patternTransform"),
+                    UniformValue::Mat3(invert3(transform)),
+                );
+                uniforms.push(String::from("patternRepeat"), UniformValue::Float(repeat.as_uniform()));
+            }
+        }
+    }
+}
+
+/// Inverts a 3x3 matrix given in column-major form (`m[col][row]`), the same
+/// layout `UniformValue::Mat3` carries. Falls back to the identity for a
+/// singular (non-invertible) matrix rather than producing NaNs.
+fn invert3(m: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+        - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+        + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]);
+
+    if det.abs() < f32::EPSILON {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    let inv_det = 1.0 / det;
+
+    [
+        [
+            (m[1][1] * m[2][2] - m[2][1] * m[1][2]) * inv_det,
+            (m[2][1] * m[0][2] - m[0][1] * m[2][2]) * inv_det,
+            (m[0][1] * m[1][2] - m[1][1] * m[0][2]) * inv_det,
+        ],
+        [
+            (m[2][0] * m[1][2] - m[1][0] * m[2][2]) * inv_det,
+            (m[0][0] * m[2][2] - m[2][0] * m[0][2]) * inv_det,
+            (m[1][0] * m[0][2] - m[0][0] * m[1][2]) * inv_det,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[2][0] * m[1][1]) * inv_det,
+            (m[2][0] * m[0][1] - m[0][0] * m[2][1]) * inv_det,
+            (m[0][0] * m[1][1] - m[1][0] * m[0][1]) * inv_det,
+        ],
+    ]
+}
+
+fn push_stops(uniforms: &mut UniformBuffer, stops: &[(f32, V4)]) {
+    let count = stops.len().min(MAX_GRADIENT_STOPS);
+    uniforms.push(String::from("gradStopCount"), UniformValue::Float(count as f32));
+    for (i, (position, color)) in stops.iter().take(MAX_GRADIENT_STOPS).enumerate() {
+        uniforms.push(format!("gradStopPos[{}]", i), UniformValue::Float(*position));
+        uniforms.push(
+            format!("gradStopCol[{}]", i),
+            UniformValue::Vec4([color.x, color.y, color.z, color.w]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    fn mat_mul(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+        let mut out = [[0.0f32; 3]; 3];
+        for col in 0..3 {
+            for row in 0..3 {
+                out[col][row] = (0..3).map(|k| a[k][row] * b[col][k]).sum();
+            }
+        }
+        out
+    }
+
+    fn assert_mat_eq(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) {
+        for col in 0..3 {
+            for row in 0..3 {
+                assert!(
+                    (a[col][row] - b[col][row]).abs() < 1e-4,
+                    "matrices differ at col {} row {}: {} vs {}",
+                    col,
+                    row,
+                    a[col][row],
+                    b[col][row]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverts_identity_to_itself() {
+        assert_mat_eq(&invert3(&IDENTITY), &IDENTITY);
+    }
+
+    #[test]
+    fn round_trips_an_invertible_matrix() {
+        // Scale by 2 in x, translate by (3, 4) (column-major, homogeneous 2D
+        // transform): a matrix the pattern UV transform would plausibly
+        // carry.
+        let m = [[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [3.0, 4.0, 1.0]];
+        let inverse = invert3(&m);
+        assert_mat_eq(&mat_mul(&m, &inverse), &IDENTITY);
+    }
+
+    #[test]
+    fn falls_back_to_identity_for_a_singular_matrix() {
+        // Second row is a multiple of the first: determinant is zero.
+        let singular = [[1.0, 2.0, 0.0], [2.0, 4.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_mat_eq(&invert3(&singular), &IDENTITY);
+    }
+}
@@ -0,0 +1,300 @@
+//! Tessellation of composition paths into the triangle meshes the GPU
+//! backends draw.
+
+use crate::{gpu::GpuVertex, Result, V4};
+use lyon_path::{iterator::PathIterator, math::Point, Builder, Path, PathEvent};
+use lyon_tessellation::{
+    BuffersBuilder,
+    FillOptions,
+    FillTessellator,
+    FillVertex,
+    StrokeOptions,
+    StrokeTessellator,
+    StrokeVertex,
+    VertexBuffers,
+};
+
+/// Tolerance used when flattening curves into line segments while walking a
+/// stroked path to place dashes. Fill and stroke tessellation use lyon's
+/// default tolerance instead.
+const FLATTEN_TOLERANCE: f32 = 0.01;
+
+/// A repeating on/off pattern stroked geometry is carved out of, e.g.
+/// `[4.0, 2.0]` draws 4 units on, 2 off, repeating.
+#[derive(Debug, Clone)]
+pub struct DashPattern {
+    pub array: Vec<f32>,
+    pub offset: f32,
+}
+
+/// How a path should be turned into geometry.
+#[derive(Debug, Clone)]
+pub enum Method {
+    Fill,
+    Stroke { width: f32, dash: Option<DashPattern> },
+}
+
+pub fn raster_path(path: Builder, method: Method, color: V4) -> Result<(Vec<GpuVertex>, Vec<u32>)> {
+    match method {
+        Method::Fill => fill(path, color),
+        Method::Stroke { width, dash } => stroke(path, width, dash, color),
+    }
+}
+
+fn fill(path: Builder, color: V4) -> Result<(Vec<GpuVertex>, Vec<u32>)> {
+    let path = path.build();
+    let mut buffers: VertexBuffers<GpuVertex, u32> = VertexBuffers::new();
+    let mut tessellator = FillTessellator::new();
+    tessellator.tessellate_path(
+        &path,
+        &FillOptions::default(),
+        &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| GpuVertex {
+            vpos: vertex.position().to_array(),
+            vcol: [color.x, color.y, color.z, color.w],
+        }),
+    )?;
+
+    Ok((buffers.vertices, buffers.indices))
+}
+
+fn stroke(
+    path: Builder,
+    width: f32,
+    dash: Option<DashPattern>,
+    color: V4,
+) -> Result<(Vec<GpuVertex>, Vec<u32>)> {
+    let path = path.build();
+    let mut buffers: VertexBuffers<GpuVertex, u32> = VertexBuffers::new();
+    let mut tessellator = StrokeTessellator::new();
+    let options = StrokeOptions::default().with_line_width(width);
+
+    let mut tessellate_one = |path: &Path| -> Result<()> {
+        tessellator.tessellate_path(
+            path,
+            &options,
+            &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| GpuVertex {
+                vpos: vertex.position().to_array(),
+                vcol: [color.x, color.y, color.z, color.w],
+            }),
+        )?;
+        Ok(())
+    };
+
+    match dash {
+        None => tessellate_one(&path)?,
+        Some(dash) => {
+            for segment in dashed_subpaths(&path, &dash) {
+                tessellate_one(&segment)?;
+            }
+        }
+    }
+
+    Ok((buffers.vertices, buffers.indices))
+}
+
+/// Walk `path`'s flattened polyline, emitting one sub-path per contiguous
+/// "on" run of `dash`. A `dash.array` that is empty or entirely zero-length
+/// is treated as a solid stroke (the whole path, undashed).
+///
+/// `dash.array` is walked as-is, including zero-length entries: a
+/// zero-length step still toggles `on` and advances the cursor (so the
+/// on/off parity the caller intended is preserved), it just contributes no
+/// path length. Filtering zero-length entries out before walking would shift
+/// that parity on every pass through a cycle that loses an odd count of
+/// entries.
+fn dashed_subpaths(path: &Path, dash: &DashPattern) -> Vec<Path> {
+    let array = &dash.array;
+    if array.is_empty() || array.iter().all(|len| *len <= 0.0) {
+        return vec![path.clone()];
+    }
+
+    let mut cursor = 0usize;
+    let mut on = true;
+    let mut remaining = array[0];
+
+    let mut advance_dash = |on: &mut bool, cursor: &mut usize, remaining: &mut f32| {
+        *cursor = (*cursor + 1) % array.len();
+        *on = !*on;
+        *remaining = array[*cursor];
+    };
+
+    // Consume `offset`, cycling through the array, before drawing anything.
+    // Zero-length entries are stepped over (still toggling `on`) without
+    // consuming any offset; the array holds at least one positive entry, so
+    // every pass consumes some offset and this always terminates.
+    let mut offset = dash.offset.max(0.0);
+    loop {
+        while remaining <= 0.0 {
+            advance_dash(&mut on, &mut cursor, &mut remaining);
+        }
+        if offset < remaining {
+            break;
+        }
+        offset -= remaining;
+        advance_dash(&mut on, &mut cursor, &mut remaining);
+    }
+    remaining -= offset;
+
+    let mut subpaths = Vec::new();
+    let mut builder: Option<Builder> = None;
+
+    for event in path.iter().flattened(FLATTEN_TOLERANCE) {
+        let (from, to) = match event {
+            PathEvent::Begin { .. } => {
+                // A new contour starts: flush whatever's in progress rather
+                // than letting the next contour's first segment get
+                // appended to it, which would draw a spurious bridge
+                // between two unrelated subpaths.
+                if let Some(b) = builder.take() {
+                    subpaths.push(b.build());
+                }
+                continue;
+            }
+            PathEvent::Line { from, to } => (from, to),
+            PathEvent::End { last, first, close: true } => (last, first),
+            PathEvent::End { .. } => {
+                if let Some(b) = builder.take() {
+                    subpaths.push(b.build());
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        let mut from = from;
+        let mut segment_left = (to - from).length();
+        let direction = if segment_left > 0.0 {
+            (to - from) / segment_left
+        } else {
+            continue;
+        };
+
+        while segment_left > 0.0 {
+            let step = remaining.min(segment_left);
+            let next: Point = from + direction * step;
+
+            if on {
+                let b = builder.get_or_insert_with(|| {
+                    let mut b = Builder::new();
+                    b.begin(from);
+                    b
+                });
+                b.line_to(next);
+            }
+
+            from = next;
+            segment_left -= step;
+            remaining -= step;
+
+            if remaining <= 0.0 {
+                if let Some(b) = builder.take() {
+                    subpaths.push(b.build());
+                }
+                // A zero-length step still toggles `on`/`cursor` even
+                // though it consumed none of `segment_left`; loop here
+                // (rather than relying on the outer `while segment_left`
+                // re-entry) so a run of several zero-length entries in a
+                // row all toggle before any further path length is drawn.
+                advance_dash(&mut on, &mut cursor, &mut remaining);
+                while remaining <= 0.0 {
+                    advance_dash(&mut on, &mut cursor, &mut remaining);
+                }
+            }
+        }
+    }
+
+    if let Some(b) = builder.take() {
+        subpaths.push(b.build());
+    }
+
+    subpaths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(from: (f32, f32), to: (f32, f32)) -> Builder {
+        let mut b = Builder::new();
+        b.begin(Point::new(from.0, from.1));
+        b.line_to(Point::new(to.0, to.1));
+        b.end(false);
+        b
+    }
+
+    fn total_length(paths: &[Path]) -> f32 {
+        paths
+            .iter()
+            .flat_map(|path| path.iter().flattened(FLATTEN_TOLERANCE))
+            .filter_map(|event| match event {
+                PathEvent::Line { from, to } => Some((to - from).length()),
+                _ => None,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn zero_length_entry_toggles_without_shifting_parity() {
+        // [4 on, 0 (instant toggle back on), 2 on, 3 off] repeating: 6 on
+        // per 9-unit cycle, not the 4-on/2-off/3-on/... alternation you'd
+        // get by dropping the zero-length entry from the cycle.
+        let path = line((0.0, 0.0), (10.0, 0.0)).build();
+        let dash = DashPattern { array: vec![4.0, 0.0, 2.0, 3.0], offset: 0.0 };
+        let subpaths = dashed_subpaths(&path, &dash);
+        // [0,4] on, [4,6] on (separate subpath: the zero-length entry still
+        // closes the run), [9,10] on at the start of the next cycle.
+        assert_eq!(subpaths.len(), 3);
+        assert!((total_length(&subpaths) - 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn all_zero_array_is_treated_as_solid() {
+        let path = line((0.0, 0.0), (10.0, 0.0)).build();
+        let dash = DashPattern { array: vec![0.0, 0.0], offset: 0.0 };
+        let subpaths = dashed_subpaths(&path, &dash);
+        assert_eq!(subpaths.len(), 1);
+        assert!((total_length(&subpaths) - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn offset_is_consumed_before_drawing() {
+        // Offsetting by 5 into [4 on, 3 off] lands 1 unit into the "off"
+        // run, leaving 2 off, then 4 on / 3 off / ... for the remaining length.
+        let path = line((0.0, 0.0), (20.0, 0.0)).build();
+        let dash = DashPattern { array: vec![4.0, 3.0], offset: 5.0 };
+        let subpaths = dashed_subpaths(&path, &dash);
+        // off[0..2] (skipped), on[2..6], off[6..9], on[9..13], off[13..16],
+        // on[16..20]: three "on" runs totalling 4 + 4 + 4 = 12.
+        assert_eq!(subpaths.len(), 3);
+        assert!((total_length(&subpaths) - 12.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn disjoint_subpaths_never_bridge() {
+        // Two separate open contours, far apart; a dash "on" run spanning
+        // both contours' shared draw call must not connect the end of the
+        // first contour to the start of the second.
+        let mut path = Builder::new();
+        path.begin(Point::new(0.0, 0.0));
+        path.line_to(Point::new(10.0, 0.0));
+        path.end(false);
+        path.begin(Point::new(100.0, 0.0));
+        path.line_to(Point::new(110.0, 0.0));
+        path.end(false);
+        let path = path.build();
+
+        let dash = DashPattern { array: vec![20.0], offset: 0.0 };
+        let subpaths = dashed_subpaths(&path, &dash);
+        assert_eq!(subpaths.len(), 2);
+        for subpath in &subpaths {
+            for event in subpath.iter().flattened(FLATTEN_TOLERANCE) {
+                if let PathEvent::Line { from, to } = event {
+                    // Neither contour is 90 units long, so a bridged
+                    // segment (connecting x=10 to x=100) would be the only
+                    // way to see a step this large.
+                    assert!((to - from).length() < 15.0);
+                }
+            }
+        }
+    }
+}
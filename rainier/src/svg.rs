@@ -0,0 +1,361 @@
+//! Vector export of a composition, independent of any `Backend`: this walks
+//! the same `Element`s `Gpu::precompose` would rasterize, but emits SVG
+//! markup instead of GPU draw calls. Useful for plotting, laser cutting, and
+//! print workflows that want resolution-independent output rather than the
+//! raster image `Gpu::read_to_ram` produces.
+
+use crate::{
+    backend::Backend,
+    gpu::Element,
+    paint::Paint,
+    raster::{DashPattern, Method},
+    V4,
+};
+#[cfg(test)]
+use crate::Point;
+#[cfg(test)]
+use lyon_path::Builder;
+use lyon_path::{Path, PathEvent};
+use rand::random;
+use std::fmt::Write as _;
+
+/// Serializes `elements` to an SVG document `width` x `height` pixels, with
+/// `(0, 0)` at the top-left corner. Elements are drawn in iteration order,
+/// same as `Gpu::precompose` composites them back-to-front.
+pub fn to_svg<B: Backend>(width: u32, height: u32, elements: impl Iterator<Item = Element<B>>) -> String {
+    let (width, height) = (width as f32, height as f32);
+    let mut defs = String::new();
+    let mut body = String::new();
+
+    for element in elements {
+        write_element(element, width, height, &mut defs, &mut body);
+    }
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+    if !defs.is_empty() {
+        let _ = write!(svg, "<defs>\n{}</defs>\n", defs);
+    }
+    svg.push_str(&body);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_element<B: Backend>(element: Element<B>, width: f32, height: f32, defs: &mut String, body: &mut String) {
+    let (fill, stroke) = paint_attrs(&element.paint, width, height, defs);
+
+    let clip_attr = element.clip.map(|clip| {
+        let id = format!("clip{:x}", random::<u64>());
+        let _ = write!(
+            defs,
+            "  <clipPath id=\"{}\"><path d=\"{}\"/></clipPath>\n",
+            id,
+            path_to_d(&clip.build(), width, height)
+        );
+        format!(" clip-path=\"url(#{})\"", id)
+    });
+
+    let d = path_to_d(&element.path.build(), width, height);
+
+    match element.raster_method {
+        Method::Fill => {
+            let _ = write!(
+                body,
+                "  <path d=\"{}\" {}{}/>\n",
+                d,
+                fill,
+                clip_attr.unwrap_or_default()
+            );
+        }
+        Method::Stroke { width: stroke_width, dash } => {
+            // Path-space coordinates span [-1, 1]; `path_to_d` scales x by
+            // `width / 2` and y by `height / 2`. Stroke widths and dash
+            // lengths (themselves path-space units) take the smaller of the
+            // two, so they match the geometry exactly only on a square
+            // canvas.
+            let scale = width.min(height) / 2.0;
+            let dasharray = dasharray_attr(dash.as_ref(), scale);
+
+            let _ = write!(
+                body,
+                "  <path d=\"{}\" fill=\"none\" {} stroke-width=\"{}\"{}{}/>\n",
+                d,
+                stroke,
+                stroke_width * scale,
+                dasharray,
+                clip_attr.unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Maps `path`'s points from OpenGL-centered `[-1, 1]` world space into
+/// `width` x `height` top-left pixel space (flipping `y`, since SVG's origin
+/// is top-left and increases downward) and walks its events into an SVG
+/// path `d` string, preserving curves rather than flattening them.
+fn path_to_d(path: &Path, width: f32, height: f32) -> String {
+    let mut d = String::new();
+    for event in path.iter() {
+        match event {
+            PathEvent::Begin { at } => {
+                let (x, y) = to_pixels(at, width, height);
+                let _ = write!(d, "M{},{} ", x, y);
+            }
+            PathEvent::Line { to, .. } => {
+                let (x, y) = to_pixels(to, width, height);
+                let _ = write!(d, "L{},{} ", x, y);
+            }
+            PathEvent::Quadratic { ctrl, to, .. } => {
+                let (cx, cy) = to_pixels(ctrl, width, height);
+                let (x, y) = to_pixels(to, width, height);
+                let _ = write!(d, "Q{},{} {},{} ", cx, cy, x, y);
+            }
+            PathEvent::Cubic { ctrl1, ctrl2, to, .. } => {
+                let (c1x, c1y) = to_pixels(ctrl1, width, height);
+                let (c2x, c2y) = to_pixels(ctrl2, width, height);
+                let (x, y) = to_pixels(to, width, height);
+                let _ = write!(d, "C{},{} {},{} {},{} ", c1x, c1y, c2x, c2y, x, y);
+            }
+            PathEvent::End { close, .. } => {
+                if close {
+                    d.push_str("Z ");
+                }
+            }
+        }
+    }
+    d.trim_end().to_string()
+}
+
+/// The inverse of the root `valora` crate's `Point::fix_coord`: maps a world
+/// coordinate in `[-1, 1]` back to `[0, 1]`. Named (rather than inlined) to
+/// match that crate's `Point::WORLD_OFFSET`/`WORLD_FACTOR` convention, since
+/// this is necessarily a second copy of the same formula, not a call to it:
+/// rainier's own `Point` here is `lyon_path::math::Point` (plain `x`/`y`
+/// floats with no coordinate-space concept of its own), and this crate has
+/// no dependency on the root crate to call its `Point::restore_coord`.
+const WORLD_OFFSET: f32 = 1.0;
+const WORLD_FACTOR: f32 = 2.0;
+
+fn to_pixels(point: lyon_path::math::Point, width: f32, height: f32) -> (f32, f32) {
+    let x = (point.x + WORLD_OFFSET) / WORLD_FACTOR * width;
+    let y = (1.0 - (point.y + WORLD_OFFSET) / WORLD_FACTOR) * height;
+    (x, y)
+}
+
+/// Returns a ` stroke-dasharray="..."` attribute for `dash`, scaling its
+/// (path-space) lengths by `scale` same as `stroke-width`, or an empty
+/// string if there's no dash or it's entirely zero-length (solid stroke).
+fn dasharray_attr(dash: Option<&DashPattern>, scale: f32) -> String {
+    dash.filter(|d| d.array.iter().any(|len| *len > 0.0))
+        .map(|d| {
+            d.array
+                .iter()
+                .map(|len| (len * scale).to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .map(|array| format!(" stroke-dasharray=\"{}\"", array))
+        .unwrap_or_default()
+}
+
+/// Returns the `fill="..."` and `stroke="..."` attributes for `paint`,
+/// pushing a `<linearGradient>`/`<radialGradient>` def (for the gradient
+/// variants) into `defs` when needed. Gradient coordinates are declared
+/// `gradientUnits="userSpaceOnUse"`, i.e. the same pixel space as the
+/// document's `viewBox`, so they need the same world-to-pixel mapping
+/// `to_pixels` applies to path points (and the same `width.min(height) / 2.0`
+/// stroke scale for `radius`), not just the `[-1, 1]` -> `[0, 1]` step.
+fn paint_attrs<B: Backend>(paint: &Paint<B>, width: f32, height: f32, defs: &mut String) -> (String, String) {
+    match paint {
+        Paint::Solid(color) => (color_attr("fill", *color), color_attr("stroke", *color)),
+        Paint::LinearGradient { from, to, stops } => {
+            let id = format!("grad{:x}", random::<u64>());
+            let (x1, y1) = to_pixels(lyon_path::math::point(from.x, from.y), width, height);
+            let (x2, y2) = to_pixels(lyon_path::math::point(to.x, to.y), width, height);
+            let _ = write!(
+                defs,
+                "  <linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">\n",
+                id, x1, y1, x2, y2
+            );
+            write_stops(defs, stops);
+            defs.push_str("  </linearGradient>\n");
+            (format!("fill=\"url(#{})\"", id), format!("stroke=\"url(#{})\"", id))
+        }
+        Paint::RadialGradient { center, radius, stops } => {
+            let id = format!("grad{:x}", random::<u64>());
+            let (cx, cy) = to_pixels(lyon_path::math::point(center.x, center.y), width, height);
+            let scale = width.min(height) / 2.0;
+            let _ = write!(
+                defs,
+                "  <radialGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" cx=\"{}\" cy=\"{}\" r=\"{}\">\n",
+                id,
+                cx,
+                cy,
+                *radius * scale
+            );
+            write_stops(defs, stops);
+            defs.push_str("  </radialGradient>\n");
+            (format!("fill=\"url(#{})\"", id), format!("stroke=\"url(#{})\"", id))
+        }
+        // A bitmap pattern has no vector representation; fall back to the
+        // paint's base color rather than dropping the element silently.
+        Paint::Pattern { .. } => {
+            let color = paint.base_color();
+            (color_attr("fill", color), color_attr("stroke", color))
+        }
+    }
+}
+
+fn write_stops(defs: &mut String, stops: &[(f32, V4)]) {
+    for (position, color) in stops {
+        let _ = write!(
+            defs,
+            "    <stop offset=\"{}\" stop-color=\"rgb({},{},{})\" stop-opacity=\"{}\"/>\n",
+            position,
+            (color.x * 255.0).round() as u8,
+            (color.y * 255.0).round() as u8,
+            (color.z * 255.0).round() as u8,
+            color.w
+        );
+    }
+}
+
+fn color_attr(attr: &str, color: V4) -> String {
+    format!(
+        "{}=\"rgb({},{},{})\" {}-opacity=\"{}\"",
+        attr,
+        (color.x * 255.0).round() as u8,
+        (color.y * 255.0).round() as u8,
+        (color.z * 255.0).round() as u8,
+        attr,
+        color.w
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GpuVertex;
+
+    /// `Paint<B>`'s gradient variants never touch `B`, but `paint_attrs` is
+    /// still generic over it; a backend that's never actually called (every
+    /// method panics) is enough to exercise those variants in a test.
+    struct NullBackend;
+
+    impl Backend for NullBackend {
+        type Program = ();
+        type Texture = ();
+        type Buffers = ();
+        type RamImage = ();
+        type SampledTexture = ();
+
+        fn new() -> crate::Result<Self> { unimplemented!() }
+        fn compile_shader(&self, _source: crate::backend::ShaderSource) -> crate::Result<()> { unimplemented!() }
+        fn build_texture(&self, _width: u32, _height: u32) -> crate::Result<()> { unimplemented!() }
+        fn build_buffers(&self, _vertices: &[GpuVertex], _indices: &[u32]) -> crate::Result<()> { unimplemented!() }
+        fn draw(
+            &self,
+            _texture: &(),
+            _buffers: &(),
+            _program: &(),
+            _uniforms: &[(String, crate::backend::UniformValue)],
+            _stencil: Option<crate::backend::StencilOp>,
+            _pattern: Option<&()>,
+        ) -> crate::Result<()> {
+            unimplemented!()
+        }
+        fn clear_stencil(&self, _texture: &()) -> crate::Result<()> { unimplemented!() }
+        fn read_to_ram(&self, _texture: &()) -> crate::Result<()> { unimplemented!() }
+        fn texture_size(&self, _texture: &()) -> (u32, u32) { unimplemented!() }
+        fn resolve_to_sampleable(&self, _texture: &()) -> crate::Result<()> { unimplemented!() }
+        fn draw_fullscreen(
+            &self,
+            _target: &(),
+            _program: &(),
+            _source: &(),
+            _buffers: &(),
+            _uniforms: &[(String, crate::backend::UniformValue)],
+        ) -> crate::Result<()> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn to_pixels_maps_world_corners_to_pixel_corners() {
+        // World space's bottom-left (-1, -1) is SVG's top-left (0, height);
+        // world space's top-right (1, 1) is SVG's bottom-right (width, 0).
+        assert_eq!(to_pixels(lyon_path::math::point(-1.0, -1.0), 200.0, 100.0), (0.0, 100.0));
+        assert_eq!(to_pixels(lyon_path::math::point(1.0, 1.0), 200.0, 100.0), (200.0, 0.0));
+        assert_eq!(to_pixels(lyon_path::math::point(0.0, 0.0), 200.0, 100.0), (100.0, 50.0));
+    }
+
+    #[test]
+    fn path_to_d_preserves_curves_and_closes() {
+        let mut builder = Builder::new();
+        builder.begin(lyon_path::math::point(-1.0, -1.0));
+        builder.line_to(lyon_path::math::point(1.0, -1.0));
+        builder.quadratic_bezier_to(lyon_path::math::point(1.0, 0.0), lyon_path::math::point(1.0, 1.0));
+        builder.end(true);
+        let path = builder.build();
+
+        let d = path_to_d(&path, 200.0, 100.0);
+        assert_eq!(d, "M0,100 L200,100 Q200,50 200,0 Z");
+    }
+
+    #[test]
+    fn dasharray_attr_scales_lengths() {
+        let dash = DashPattern { array: vec![4.0, 2.0], offset: 0.0 };
+        assert_eq!(dasharray_attr(Some(&dash), 10.0), " stroke-dasharray=\"40,20\"");
+    }
+
+    #[test]
+    fn dasharray_attr_omitted_for_solid_or_absent() {
+        assert_eq!(dasharray_attr(None, 10.0), "");
+        let solid = DashPattern { array: vec![0.0, 0.0], offset: 0.0 };
+        assert_eq!(dasharray_attr(Some(&solid), 10.0), "");
+    }
+
+    #[test]
+    fn write_stops_formats_rgb_and_opacity() {
+        let mut defs = String::new();
+        write_stops(&mut defs, &[(0.0, V4::new(1.0, 0.0, 0.5, 0.5))]);
+        assert_eq!(
+            defs,
+            "    <stop offset=\"0\" stop-color=\"rgb(255,0,128)\" stop-opacity=\"0.5\"/>\n"
+        );
+    }
+
+    #[test]
+    fn linear_gradient_coords_are_pixel_space_not_unit_space() {
+        // A gradient spanning the whole world-space diagonal must land on
+        // the pixel-space diagonal, same as `path_to_d`'s points (and not at
+        // the `[0, 1]` unit-space coordinates `gradientUnits="userSpaceOnUse"`
+        // would misinterpret as a 1x1px region in the image's top-left).
+        let paint: Paint<NullBackend> = Paint::LinearGradient {
+            from: Point { x: -1.0, y: -1.0 },
+            to: Point { x: 1.0, y: 1.0 },
+            stops: vec![(0.0, V4::new(1.0, 1.0, 1.0, 1.0)), (1.0, V4::new(0.0, 0.0, 0.0, 1.0))],
+        };
+        let mut defs = String::new();
+        paint_attrs(&paint, 200.0, 100.0, &mut defs);
+        assert!(defs.contains("x1=\"0\" y1=\"100\" x2=\"200\" y2=\"0\""), "{}", defs);
+    }
+
+    #[test]
+    fn radial_gradient_coords_scale_center_and_radius_to_pixels() {
+        let paint: Paint<NullBackend> = Paint::RadialGradient {
+            center: Point { x: 0.0, y: 0.0 },
+            radius: 0.5,
+            stops: vec![(0.0, V4::new(1.0, 1.0, 1.0, 1.0))],
+        };
+        let mut defs = String::new();
+        paint_attrs(&paint, 200.0, 100.0, &mut defs);
+        // Center maps to the canvas midpoint; radius 0.5 (world units) scales
+        // by `width.min(height) / 2.0` = 50, i.e. 25px, not 0.25 (unit space).
+        assert!(defs.contains("cx=\"100\" cy=\"50\" r=\"25\""), "{}", defs);
+    }
+}